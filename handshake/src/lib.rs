@@ -0,0 +1,275 @@
+//! `TestHandshake`, a transport authentication upgrade that proves peer identity with a mutual
+//! challenge-response exchange before resolving to the remote [`PeerId`].
+//!
+//! Wire format, run identically by both sides over the raw connection:
+//! 1. send our protobuf-encoded [`PublicKey`] and a random nonce
+//! 2. receive the peer's public key and nonce
+//! 3. send a signature over `DOMAIN_SEPARATOR || our_nonce || their_nonce`
+//! 4. receive the peer's signature over `DOMAIN_SEPARATOR || their_nonce || our_nonce` and
+//!    verify it against the peer's advertised public key
+//!
+//! Each nonce is freshly randomly generated per handshake, so a captured signature is bound to
+//! that run's specific nonce pair and cannot be replayed against a different one; no persistent
+//! replay cache is needed.
+
+use std::io;
+
+use futures::channel::mpsc::UnboundedSender;
+use futures::future::BoxFuture;
+use futures::prelude::*;
+use libp2p::core::upgrade::{InboundConnectionUpgrade, OutboundConnectionUpgrade};
+use libp2p::core::UpgradeInfo;
+use libp2p::identity::{self, PublicKey};
+use libp2p::PeerId;
+use rand::RngCore;
+use thiserror::Error;
+
+const PROTOCOL_NAME: &str = "/test-handshake/1.0.0";
+const DOMAIN_SEPARATOR: &[u8] = b"libp2p-test-handshake/1.0.0/challenge";
+const NONCE_LEN: usize = 32;
+const MAX_FRAME_LEN: u32 = 8 * 1024;
+
+/// Errors returned while running [`TestHandshake`].
+#[derive(Debug, Error)]
+pub enum HandshakeError {
+    #[error("i/o error during handshake: {0}")]
+    Io(#[from] io::Error),
+    #[error("peer sent an oversized frame or a nonce of the wrong length")]
+    FrameTooLarge,
+    #[error("failed to decode peer's public key")]
+    InvalidPublicKey,
+    #[error("failed to sign challenge: {0}")]
+    Signing(#[from] identity::SigningError),
+    #[error("peer's signature did not verify")]
+    InvalidSignature,
+}
+
+/// Lifecycle events reported by a running [`TestHandshake`], for observability (see
+/// [`TestHandshake::with_events`]).
+#[derive(Clone, Debug)]
+pub enum HandshakeEvent {
+    /// The handshake exchange has started on a newly opened stream.
+    Started,
+    /// The handshake completed and authenticated `peer`.
+    Succeeded { peer: PeerId },
+    /// The handshake failed or was aborted.
+    Failed,
+}
+
+/// Authenticates the remote peer with a mutual challenge-response exchange and resolves to its
+/// [`PeerId`].
+#[derive(Clone)]
+pub struct TestHandshake {
+    local_key: identity::Keypair,
+    events: Option<UnboundedSender<HandshakeEvent>>,
+}
+
+impl TestHandshake {
+    pub fn new(local_key: identity::Keypair) -> Self {
+        Self {
+            local_key,
+            events: None,
+        }
+    }
+
+    /// Like [`TestHandshake::new`], but reports [`HandshakeEvent`]s for every run over `events`.
+    pub fn with_events(local_key: identity::Keypair, events: UnboundedSender<HandshakeEvent>) -> Self {
+        Self {
+            local_key,
+            events: Some(events),
+        }
+    }
+}
+
+impl UpgradeInfo for TestHandshake {
+    type Info = &'static str;
+    type InfoIter = std::iter::Once<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        std::iter::once(PROTOCOL_NAME)
+    }
+}
+
+impl<C> InboundConnectionUpgrade<C> for TestHandshake
+where
+    C: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    type Output = (PeerId, C);
+    type Error = HandshakeError;
+    type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn upgrade_inbound(self, socket: C, _info: Self::Info) -> Self::Future {
+        Box::pin(run_handshake(self.local_key, self.events, socket))
+    }
+}
+
+impl<C> OutboundConnectionUpgrade<C> for TestHandshake
+where
+    C: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    type Output = (PeerId, C);
+    type Error = HandshakeError;
+    type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn upgrade_outbound(self, socket: C, _info: Self::Info) -> Self::Future {
+        Box::pin(run_handshake(self.local_key, self.events, socket))
+    }
+}
+
+/// Drives the exchange described in the module docs and reports the outcome over `events`, if
+/// any. Inbound and outbound sides are symmetric, so a single function drives both.
+async fn run_handshake<C>(
+    local_key: identity::Keypair,
+    events: Option<UnboundedSender<HandshakeEvent>>,
+    mut socket: C,
+) -> Result<(PeerId, C), HandshakeError>
+where
+    C: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    if let Some(tx) = &events {
+        let _ = tx.unbounded_send(HandshakeEvent::Started);
+    }
+
+    let outcome = authenticate(&local_key, &mut socket).await;
+
+    if let Some(tx) = &events {
+        let _ = tx.unbounded_send(match &outcome {
+            Ok(peer) => HandshakeEvent::Succeeded { peer: *peer },
+            Err(_) => HandshakeEvent::Failed,
+        });
+    }
+
+    outcome.map(|peer| (peer, socket))
+}
+
+/// Runs the actual challenge-response exchange, returning the authenticated peer's [`PeerId`].
+async fn authenticate<C>(local_key: &identity::Keypair, socket: &mut C) -> Result<PeerId, HandshakeError>
+where
+    C: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut local_nonce = [0u8; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut local_nonce);
+
+    write_frame(socket, &local_key.public().encode_protobuf()).await?;
+    write_frame(socket, &local_nonce).await?;
+
+    let remote_public_key_bytes = read_frame(socket).await?;
+    let remote_public_key = PublicKey::try_decode_protobuf(&remote_public_key_bytes)
+        .map_err(|_| HandshakeError::InvalidPublicKey)?;
+    let remote_peer_id = PeerId::from_public_key(&remote_public_key);
+
+    let remote_nonce_bytes = read_frame(socket).await?;
+    let remote_nonce: [u8; NONCE_LEN] = remote_nonce_bytes
+        .try_into()
+        .map_err(|_| HandshakeError::FrameTooLarge)?;
+
+    let local_signature = local_key.sign(&challenge(&local_nonce, &remote_nonce))?;
+    write_frame(socket, &local_signature).await?;
+
+    let remote_signature = read_frame(socket).await?;
+    if !remote_public_key.verify(&challenge(&remote_nonce, &local_nonce), &remote_signature) {
+        return Err(HandshakeError::InvalidSignature);
+    }
+
+    Ok(remote_peer_id)
+}
+
+fn challenge(first_nonce: &[u8], second_nonce: &[u8]) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(DOMAIN_SEPARATOR.len() + first_nonce.len() + second_nonce.len());
+    payload.extend_from_slice(DOMAIN_SEPARATOR);
+    payload.extend_from_slice(first_nonce);
+    payload.extend_from_slice(second_nonce);
+    payload
+}
+
+async fn write_frame<C: AsyncWrite + Unpin>(socket: &mut C, bytes: &[u8]) -> io::Result<()> {
+    socket.write_all(&(bytes.len() as u32).to_be_bytes()).await?;
+    socket.write_all(bytes).await?;
+    socket.flush().await
+}
+
+async fn read_frame<C: AsyncRead + Unpin>(socket: &mut C) -> Result<Vec<u8>, HandshakeError> {
+    let mut len_bytes = [0u8; 4];
+    socket.read_exact(&mut len_bytes).await?;
+    let len = u32::from_be_bytes(len_bytes);
+    if len > MAX_FRAME_LEN {
+        return Err(HandshakeError::FrameTooLarge);
+    }
+    let mut buf = vec![0u8; len as usize];
+    socket.read_exact(&mut buf).await?;
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_ringbuf::Endpoint;
+    use libp2p::identity::Keypair;
+
+    #[async_std::test]
+    async fn authenticates_both_sides_with_matching_peer_ids() {
+        let local_key = Keypair::generate_ed25519();
+        let remote_key = Keypair::generate_ed25519();
+        let local_peer_id = PeerId::from(local_key.public());
+        let remote_peer_id = PeerId::from(remote_key.public());
+
+        let (mut local_socket, mut remote_socket) = Endpoint::pair(4096, 4096);
+
+        let (local_result, remote_result) = futures::join!(
+            authenticate(&local_key, &mut local_socket),
+            authenticate(&remote_key, &mut remote_socket),
+        );
+
+        assert_eq!(local_result.unwrap(), remote_peer_id);
+        assert_eq!(remote_result.unwrap(), local_peer_id);
+    }
+
+    #[async_std::test]
+    async fn rejects_an_invalid_signature() {
+        let local_key = Keypair::generate_ed25519();
+        let remote_key = Keypair::generate_ed25519();
+
+        let (mut local_socket, mut remote_socket) = Endpoint::pair(4096, 4096);
+
+        let local_run = authenticate(&local_key, &mut local_socket);
+        let fake_remote = async {
+            write_frame(&mut remote_socket, &remote_key.public().encode_protobuf()).await?;
+            write_frame(&mut remote_socket, &[0u8; NONCE_LEN]).await?;
+            let _ = read_frame(&mut remote_socket).await?;
+            let _ = read_frame(&mut remote_socket).await?;
+            // Send a signature that doesn't correspond to the expected challenge.
+            write_frame(&mut remote_socket, &remote_key.sign(b"not the challenge").unwrap()).await?;
+            let _ = read_frame(&mut remote_socket).await?;
+            Ok::<(), HandshakeError>(())
+        };
+
+        let (local_result, fake_result) = futures::join!(local_run, fake_remote);
+
+        fake_result.unwrap();
+        assert!(matches!(local_result, Err(HandshakeError::InvalidSignature)));
+    }
+
+    #[async_std::test]
+    async fn reports_started_and_succeeded_events() {
+        let local_key = Keypair::generate_ed25519();
+        let remote_key = Keypair::generate_ed25519();
+        let remote_peer_id = PeerId::from(remote_key.public());
+
+        let (local_socket, mut remote_socket) = Endpoint::pair(4096, 4096);
+        let (tx, mut rx) = futures::channel::mpsc::unbounded();
+
+        let (local_result, _) = futures::join!(
+            run_handshake(local_key, Some(tx), local_socket),
+            authenticate(&remote_key, &mut remote_socket),
+        );
+
+        let (peer, _) = local_result.unwrap();
+        assert_eq!(peer, remote_peer_id);
+
+        assert!(matches!(rx.try_recv().unwrap(), HandshakeEvent::Started));
+        assert!(matches!(
+            rx.try_recv().unwrap(),
+            HandshakeEvent::Succeeded { peer } if peer == remote_peer_id
+        ));
+    }
+}
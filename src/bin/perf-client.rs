@@ -0,0 +1,104 @@
+use std::error::Error;
+use std::time::{Duration, Instant};
+
+use futures::prelude::*;
+use libp2p::swarm::SwarmEvent;
+use libp2p::{identity, yamux, Multiaddr, SwarmBuilder};
+use libp2p_perf::client;
+use libp2p_perf::{Final, RunParams, RunUpdate};
+use libp2p_test_handshake::TestHandshake;
+use thiserror::Error;
+
+/// Errors parsing the `perf-client <multiaddr> [upload-bytes] [download-bytes]` CLI.
+#[derive(Debug, Error)]
+enum CliError {
+    #[error("usage: perf-client <multiaddr> [upload-bytes] [download-bytes]")]
+    MissingAddress,
+    #[error("invalid multiaddr: {0}")]
+    InvalidAddress(#[from] libp2p::multiaddr::Error),
+    #[error("invalid byte count: {0}")]
+    InvalidByteCount(#[from] std::num::ParseIntError),
+}
+
+const DEFAULT_TRANSFER_BYTES: &str = "1048576";
+
+#[async_std::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let mut args = std::env::args().skip(1);
+    let addr: Multiaddr = args
+        .next()
+        .ok_or(CliError::MissingAddress)?
+        .parse()
+        .map_err(CliError::InvalidAddress)?;
+    let upload_bytes: usize = args
+        .next()
+        .unwrap_or_else(|| DEFAULT_TRANSFER_BYTES.to_string())
+        .parse()
+        .map_err(CliError::InvalidByteCount)?;
+    let download_bytes: usize = args
+        .next()
+        .unwrap_or_else(|| DEFAULT_TRANSFER_BYTES.to_string())
+        .parse()
+        .map_err(CliError::InvalidByteCount)?;
+
+    let mut swarm = SwarmBuilder::with_new_identity()
+        .with_async_std()
+        .with_tcp(
+            Default::default(),
+            |local_key: &identity::Keypair| {
+                Ok::<_, std::convert::Infallible>(TestHandshake::new(local_key.clone()))
+            },
+            yamux::Config::default,
+        )?
+        .with_behaviour(|_local_key| client::Behaviour::default())?
+        .with_swarm_config(|cfg| cfg.with_idle_connection_timeout(Duration::from_secs(30)))
+        .build();
+
+    let connect_start = Instant::now();
+    swarm.dial(addr.clone())?;
+
+    let server_peer_id = loop {
+        match swarm.select_next_some().await {
+            SwarmEvent::ConnectionEstablished { peer_id, .. } => break peer_id,
+            SwarmEvent::OutgoingConnectionError { error, .. } => {
+                return Err(format!("failed to connect to {addr}: {error:?}").into())
+            }
+            _ => {}
+        }
+    };
+    let connect_duration = connect_start.elapsed();
+
+    swarm.behaviour_mut().perf(
+        server_peer_id,
+        RunParams {
+            to_send: upload_bytes,
+            to_receive: download_bytes,
+        },
+    )?;
+
+    loop {
+        match swarm.select_next_some().await {
+            SwarmEvent::Behaviour(client::Event {
+                result: Ok(RunUpdate::Final(Final { duration })),
+                ..
+            }) => {
+                let upload_bandwidth = upload_bytes as f64 / duration.upload.as_secs_f64();
+                let download_bandwidth = download_bytes as f64 / duration.download.as_secs_f64();
+                println!("Connect + handshake time: {connect_duration:?}");
+                println!(
+                    "Upload bandwidth: {:.2} MiB/s",
+                    upload_bandwidth / 1024.0 / 1024.0
+                );
+                println!(
+                    "Download bandwidth: {:.2} MiB/s",
+                    download_bandwidth / 1024.0 / 1024.0
+                );
+                return Ok(());
+            }
+            SwarmEvent::Behaviour(client::Event { result: Err(e), .. }) => {
+                return Err(format!("perf run failed: {e:?}").into())
+            }
+            _ => {}
+        }
+    }
+}
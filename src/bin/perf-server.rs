@@ -0,0 +1,39 @@
+use std::error::Error;
+use std::time::Duration;
+
+use futures::prelude::*;
+use libp2p::swarm::SwarmEvent;
+use libp2p::{identity, yamux, SwarmBuilder};
+use libp2p_perf::server;
+use libp2p_test_handshake::TestHandshake;
+
+/// Sinks whatever bytes `perf-client` sends and sends back as many bytes as it requests, over
+/// connections authenticated with `TestHandshake`. The client reports the connect + handshake
+/// time separately from the upload and download bandwidth of the transfer itself.
+#[async_std::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let mut swarm = SwarmBuilder::with_new_identity()
+        .with_async_std()
+        .with_tcp(
+            Default::default(),
+            |local_key: &identity::Keypair| {
+                Ok::<_, std::convert::Infallible>(TestHandshake::new(local_key.clone()))
+            },
+            yamux::Config::default,
+        )?
+        .with_behaviour(|_local_key| server::Behaviour::default())?
+        .with_swarm_config(|cfg| cfg.with_idle_connection_timeout(Duration::from_secs(30)))
+        .build();
+
+    println!("Local peer id: {:?}", swarm.local_peer_id());
+
+    swarm.listen_on("/ip4/0.0.0.0/tcp/0".parse()?)?;
+
+    loop {
+        match swarm.select_next_some().await {
+            SwarmEvent::NewListenAddr { address, .. } => println!("Listening on - {address:?}"),
+            SwarmEvent::Behaviour(event) => println!("Got perf event - {event:?}"),
+            event => println!("Got event - {event:?}"),
+        }
+    }
+}
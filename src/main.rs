@@ -1,39 +1,96 @@
 use std::error::Error;
+use std::time::Duration;
 
+use futures::channel::mpsc;
 use futures::prelude::*;
-use libp2p::{identity, Multiaddr, PeerId, ping, swarm::{keep_alive, NetworkBehaviour, SwarmBuilder, SwarmEvent}, tcp, Transport, yamux};
-use libp2p::core::upgrade::Version;
+use libp2p::{allow_block_list, connection_limits, identity, Multiaddr, PeerId, ping, swarm::{NetworkBehaviour, SwarmEvent}, yamux, SwarmBuilder};
+use libp2p::swarm::{DialError, ListenError};
 use libp2p_test_handshake::TestHandshake;
 
+mod metrics;
+
 #[async_std::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    let local_key = identity::Keypair::generate_ed25519();
-    let local_peer_id = PeerId::from(local_key.public());
-    println!("Local peer id: {local_peer_id:?}");
+    let mut args = std::env::args().skip(1);
+    let remote_addr = args.next();
+    let blocked_peers = args
+        .map(|arg| arg.parse::<PeerId>())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let (handshake_events_tx, mut handshake_events_rx) = mpsc::unbounded();
+
+    let connection_limits = connection_limits::ConnectionLimits::default()
+        .with_max_pending_incoming(Some(10))
+        .with_max_pending_outgoing(Some(10))
+        .with_max_established_incoming(Some(50))
+        .with_max_established_outgoing(Some(50))
+        .with_max_established_per_peer(Some(2));
 
-    let transport = tcp::async_io::Transport::default()
-        .upgrade(Version::V1Lazy)
-        .authenticate(TestHandshake::new(local_key))
-        .multiplex(yamux::Config::default())
-        .boxed();
+    let mut swarm = SwarmBuilder::with_new_identity()
+        .with_async_std()
+        .with_tcp(
+            Default::default(),
+            move |local_key: &identity::Keypair| {
+                Ok::<_, std::convert::Infallible>(TestHandshake::with_events(
+                    local_key.clone(),
+                    handshake_events_tx.clone(),
+                ))
+            },
+            yamux::Config::default,
+        )?
+        .with_behaviour(|_local_key| Behaviour {
+            ping: ping::Behaviour::default(),
+            allow_block_list: allow_block_list::Behaviour::default(),
+            connection_limits: connection_limits::Behaviour::new(connection_limits),
+        })?
+        .with_swarm_config(|cfg| cfg.with_idle_connection_timeout(Duration::from_secs(30)))
+        .build();
 
-    let mut swarm =
-        SwarmBuilder::with_async_std_executor(transport, Behaviour::default(), local_peer_id)
-            .build();
+    println!("Local peer id: {:?}", swarm.local_peer_id());
+
+    for peer in blocked_peers {
+        swarm.behaviour_mut().allow_block_list.block_peer(peer);
+        println!("Blocking peer {peer}");
+    }
 
     swarm.listen_on("/ip4/0.0.0.0/tcp/0".parse()?)?;
 
-    if let Some(addr) = std::env::args().nth(1) {
+    if let Some(addr) = remote_addr {
         let remote: Multiaddr = addr.parse()?;
         swarm.dial(remote)?;
         println!("Dialed {addr}")
     }
 
+    let recorder = metrics::Recorder::new();
+    if let Ok(metrics_addr) = std::env::var("METRICS_ADDR") {
+        let recorder_for_server = recorder.clone();
+        async_std::task::spawn(async move {
+            if let Err(e) = metrics::serve(&recorder_for_server, &metrics_addr).await {
+                eprintln!("Metrics server error: {e:?}");
+            }
+        });
+    }
+
+    let recorder_for_handshake = recorder.clone();
+    async_std::task::spawn(async move {
+        while let Some(event) = handshake_events_rx.next().await {
+            recorder_for_handshake.record_handshake_event(&event);
+        }
+    });
+
     loop {
-        match swarm.select_next_some().await {
+        let event = swarm.select_next_some().await;
+        recorder.record(&event);
+        match event {
             SwarmEvent::NewListenAddr { address, .. } => println!("Listening on - {address:?}"),
             SwarmEvent::Behaviour(event) => println!("Got behavior event - {event:?}"),
+            SwarmEvent::OutgoingConnectionError { error: DialError::Denied { cause }, .. } => {
+                println!("Outgoing connection denied - {cause:?}")
+            }
             SwarmEvent::OutgoingConnectionError { error, .. } => panic!("Outgoing connection error: {error:?}"),
+            SwarmEvent::IncomingConnectionError { error: ListenError::Denied { cause }, .. } => {
+                println!("Incoming connection denied - {cause:?}")
+            }
             SwarmEvent::IncomingConnectionError { error, .. } => panic!("Incoming connection error: {error:?}"),
             event => println!("Got event - {event:?}")
         }
@@ -42,10 +99,23 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
 /// Our network behaviour.
 ///
-/// For illustrative purposes, this includes the [`KeepAlive`](keep_alive::Behaviour) behaviour so a continuous sequence of
-/// pings can be observed.
-#[derive(NetworkBehaviour, Default)]
+/// Connections are no longer kept alive indefinitely by a dedicated behaviour; instead `main`
+/// configures an `idle_connection_timeout` on the swarm so a connection survives for a bounded
+/// window after no handler asks for it to be kept alive any more before it is closed.
+///
+/// `allow_block_list` layers identity-based access control on top of the `TestHandshake`
+/// authentication: peers are denied at connection-establishment time (and disconnected if they
+/// are blocked while already connected). Trailing CLI arguments (after the optional remote
+/// multiaddr to dial) are parsed as `PeerId`s and blocked at startup via `block_peer`. Swap the
+/// generic parameter to [`allow_block_list::AllowedPeers`] to flip from blocklist to allowlist
+/// mode.
+///
+/// `connection_limits` protects the handshake upgrade itself: since `TestHandshake` runs as
+/// part of transport authentication, a flood of inbound dials would otherwise be able to
+/// exhaust memory before any other behaviour gets a chance to reject them.
+#[derive(NetworkBehaviour)]
 struct Behaviour {
-    keep_alive: keep_alive::Behaviour,
     ping: ping::Behaviour,
+    allow_block_list: allow_block_list::Behaviour<allow_block_list::BlockedPeers>,
+    connection_limits: connection_limits::Behaviour,
 }
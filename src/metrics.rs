@@ -0,0 +1,177 @@
+//! Opt-in Prometheus/OpenMetrics instrumentation for the handshake + ping event loop.
+//!
+//! [`Recorder`] owns the [`Registry`] and is fed every [`SwarmEvent`] from `main`'s loop. A
+//! small `tide` server exposes the registry for scraping via [`serve`].
+
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+
+use libp2p::core::multiaddr::Protocol;
+use libp2p::swarm::{DialError, ListenError, SwarmEvent};
+use libp2p::Multiaddr;
+use libp2p_test_handshake::HandshakeEvent;
+use prometheus_client::encoding::{EncodeLabelSet, text::encode};
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::registry::Registry;
+
+use crate::BehaviourEvent;
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct ProtocolLabels {
+    protocols: String,
+}
+
+/// Records connection-lifecycle and handshake counters and hands them off for scraping.
+///
+/// Cheaply [`Clone`]able: the underlying counters and registry are reference-counted, so the
+/// event loop and the scrape server can each hold their own handle to the same state.
+#[derive(Clone)]
+pub struct Recorder {
+    connections_established: Family<ProtocolLabels, Counter>,
+    connections_closed: Family<ProtocolLabels, Counter>,
+    connections_denied: Family<ProtocolLabels, Counter>,
+    handshakes_started: Counter,
+    handshakes_succeeded: Counter,
+    handshakes_failed: Counter,
+    registry: Arc<Mutex<Registry>>,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        let mut registry = Registry::default();
+
+        let connections_established = Family::default();
+        registry.register(
+            "connections_established",
+            "Number of connections established",
+            connections_established.clone(),
+        );
+
+        let connections_closed = Family::default();
+        registry.register(
+            "connections_closed",
+            "Number of connections closed",
+            connections_closed.clone(),
+        );
+
+        let connections_denied = Family::default();
+        registry.register(
+            "connections_denied",
+            "Number of connections denied, e.g. by the connection-limits or allow/block-list behaviours",
+            connections_denied.clone(),
+        );
+
+        let handshakes_started = Counter::default();
+        registry.register(
+            "handshakes_started",
+            "Number of TestHandshake upgrades started",
+            handshakes_started.clone(),
+        );
+
+        let handshakes_succeeded = Counter::default();
+        registry.register(
+            "handshakes_succeeded",
+            "Number of TestHandshake upgrades that authenticated a peer",
+            handshakes_succeeded.clone(),
+        );
+
+        let handshakes_failed = Counter::default();
+        registry.register(
+            "handshakes_failed",
+            "Number of TestHandshake upgrades that failed",
+            handshakes_failed.clone(),
+        );
+
+        Self {
+            connections_established,
+            connections_closed,
+            connections_denied,
+            handshakes_started,
+            handshakes_succeeded,
+            handshakes_failed,
+            registry: Arc::new(Mutex::new(registry)),
+        }
+    }
+
+    /// Folds a single swarm event into the connection-lifecycle counters.
+    pub fn record(&self, event: &SwarmEvent<BehaviourEvent>) {
+        match event {
+            SwarmEvent::ConnectionEstablished { endpoint, .. } => {
+                let labels = ProtocolLabels {
+                    protocols: protocol_stack(endpoint.get_remote_address()),
+                };
+                self.connections_established.get_or_create(&labels).inc();
+            }
+            SwarmEvent::ConnectionClosed { endpoint, .. } => {
+                let labels = ProtocolLabels {
+                    protocols: protocol_stack(endpoint.get_remote_address()),
+                };
+                self.connections_closed.get_or_create(&labels).inc();
+            }
+            SwarmEvent::OutgoingConnectionError { error: DialError::Denied { .. }, .. } => {
+                self.connections_denied.get_or_create(&ProtocolLabels { protocols: String::new() }).inc();
+            }
+            SwarmEvent::IncomingConnectionError { error: ListenError::Denied { .. }, send_back_addr, .. } => {
+                let labels = ProtocolLabels { protocols: protocol_stack(send_back_addr) };
+                self.connections_denied.get_or_create(&labels).inc();
+            }
+            _ => {}
+        }
+    }
+
+    /// Folds a single [`HandshakeEvent`] emitted by `TestHandshake` itself into the handshake
+    /// counters, so they reflect the authentication upgrade rather than generic dial/listen
+    /// outcomes that occur before it ever runs.
+    pub fn record_handshake_event(&self, event: &HandshakeEvent) {
+        match event {
+            HandshakeEvent::Started => self.handshakes_started.inc(),
+            HandshakeEvent::Succeeded { .. } => self.handshakes_succeeded.inc(),
+            HandshakeEvent::Failed => self.handshakes_failed.inc(),
+        };
+    }
+
+    fn registry_handle(&self) -> Arc<Mutex<Registry>> {
+        self.registry.clone()
+    }
+}
+
+/// Joins the protocol tags of a [`Multiaddr`] with `+`, e.g. `ip4+tcp`.
+fn protocol_stack(addr: &Multiaddr) -> String {
+    let mut out = String::new();
+    for protocol in addr.iter() {
+        if !out.is_empty() {
+            out.push('+');
+        }
+        let _ = write!(out, "{}", protocol_tag(&protocol));
+    }
+    out
+}
+
+fn protocol_tag(protocol: &Protocol<'_>) -> &'static str {
+    match protocol {
+        Protocol::Ip4(_) => "ip4",
+        Protocol::Ip6(_) => "ip6",
+        Protocol::Tcp(_) => "tcp",
+        Protocol::Udp(_) => "udp",
+        Protocol::Quic | Protocol::QuicV1 => "quic",
+        Protocol::Ws(_) | Protocol::Wss(_) => "ws",
+        Protocol::P2p(_) => "p2p",
+        _ => "other",
+    }
+}
+
+/// Serves the registry's OpenMetrics text exposition at `GET /metrics` on `addr`.
+pub async fn serve(recorder: &Recorder, addr: &str) -> std::io::Result<()> {
+    let mut app = tide::with_state(recorder.registry_handle());
+    app.at("/metrics").get(|req: tide::Request<Arc<Mutex<Registry>>>| async move {
+        let mut encoded = String::new();
+        encode(&mut encoded, &req.state().lock().unwrap())
+            .map_err(|e| tide::Error::from_str(500, e.to_string()))?;
+        Ok(tide::Response::builder(200)
+            .body(encoded)
+            .content_type("application/openmetrics-text; version=1.0.0; charset=utf-8")
+            .build())
+    });
+    app.listen(addr).await
+}